@@ -0,0 +1,265 @@
+use crate::{Args, HexdumpLevel, UsbId};
+use bluer::Address;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use log::Level;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+// module name for logging engine
+const NAME: &str = "<i><bright-black> config: </>";
+
+/// File-backed configuration mirroring every [`Args`] field.
+///
+/// Every field is optional: a missing key simply leaves the corresponding
+/// built-in default (or CLI value) in place. This struct is only ever used to
+/// *layer* values onto the parsed [`Args`], see [`layer`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    advertise: Option<bool>,
+    debug: Option<bool>,
+    hexdump_level: Option<HexdumpLevel>,
+    capture: Option<PathBuf>,
+    legacy: Option<bool>,
+    connect: Option<String>,
+    logfile: Option<PathBuf>,
+    stats_interval: Option<u16>,
+    udc: Option<String>,
+    iface: Option<String>,
+    hostapd_conf: Option<PathBuf>,
+    btalias: Option<String>,
+    keepalive: Option<bool>,
+    timeout_secs: Option<u16>,
+    mitm: Option<bool>,
+    dpi: Option<u16>,
+    remove_tap_restriction: Option<bool>,
+    video_in_motion: Option<bool>,
+    disable_media_sink: Option<bool>,
+    disable_tts_sink: Option<bool>,
+    developer_mode: Option<bool>,
+    wired: Option<String>,
+    dhu: Option<bool>,
+}
+
+impl Config {
+    /// Read and deserialize a config file, picking the format from the
+    /// extension (`.yaml`/`.yml` → YAML, everything else → TOML).
+    fn load(path: &Path) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&raw).map_err(|e| e.to_string())
+            }
+            _ => toml::from_str(&raw).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Layer the config file over the parsed [`Args`] in place.
+///
+/// Precedence is CLI flags > config file > built-in defaults: a file value is
+/// only applied to a field that the user did not pass on the command line, as
+/// reported by clap's [`ValueSource`]. A file that cannot be read or parsed is
+/// reported as an error only when it was given with `--config`; at the default
+/// path any such failure is ignored silently (the default path simply may not
+/// exist on a fresh install).
+///
+/// Layering must run *before* `logging_init` so a file-supplied `debug` takes
+/// effect, which means the global logger is not installed yet. Diagnostics are
+/// therefore buffered and returned as `(level, message)` pairs for the caller
+/// to emit once logging is up, instead of being swallowed by the `log` facade.
+#[must_use]
+pub fn layer(args: &mut Args, matches: &ArgMatches) -> Vec<(Level, String)> {
+    let mut diag: Vec<(Level, String)> = Vec::new();
+    let config = match Config::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            // only report it if the user explicitly pointed us at a file
+            if matches.value_source("config") == Some(ValueSource::CommandLine) {
+                diag.push((
+                    Level::Error,
+                    format!("{} Could not read {:?}: {}", NAME, args.config, e),
+                ));
+            }
+            return diag;
+        }
+    };
+    diag.push((
+        Level::Info,
+        format!("{} ⚙️ Loaded config file: {:?}", NAME, args.config),
+    ));
+
+    // a field is eligible to be overridden by the file only when it was NOT
+    // supplied on the command line
+    let from_cli = |name: &str| matches.value_source(name) == Some(ValueSource::CommandLine);
+
+    macro_rules! overlay {
+        ($name:literal, $field:ident) => {
+            if !from_cli($name) {
+                if let Some(v) = config.$field {
+                    args.$field = v;
+                }
+            }
+        };
+    }
+
+    overlay!("advertise", advertise);
+    overlay!("debug", debug);
+    overlay!("legacy", legacy);
+    overlay!("logfile", logfile);
+    overlay!("stats_interval", stats_interval);
+    overlay!("iface", iface);
+    overlay!("hostapd_conf", hostapd_conf);
+    overlay!("keepalive", keepalive);
+    overlay!("timeout_secs", timeout_secs);
+    overlay!("mitm", mitm);
+    overlay!("dhu", dhu);
+
+    // `hexdump_level` carries clap's `requires("debug")` constraint, so mirror
+    // it for the file path: only apply a file-sourced level when debug is on
+    // (from the CLI or this same file), warn-and-skip otherwise.
+    if !from_cli("hexdump_level") {
+        if let Some(v) = config.hexdump_level {
+            if args.debug {
+                args.hexdump_level = v;
+            } else {
+                diag.push((
+                    Level::Warn,
+                    format!("{} Ignoring config `hexdump_level`: requires `debug`", NAME),
+                ));
+            }
+        }
+    }
+
+    // Option<T> fields keep their own None default, so only overlay them when
+    // the CLI didn't set them and the file actually provides a value
+    if !from_cli("udc") {
+        if let Some(v) = config.udc {
+            args.udc = Some(v);
+        }
+    }
+    if !from_cli("btalias") {
+        if let Some(v) = config.btalias {
+            args.btalias = Some(v);
+        }
+    }
+    // MITM-dependent fields mirror clap's `requires("mitm")` constraint: a file
+    // may only set them when MITM is enabled (on the CLI or in the same file).
+    // Applying them otherwise would silently produce the inconsistent state
+    // clap rejects on the command line, so warn and skip instead.
+    macro_rules! overlay_mitm {
+        ($name:literal, $field:ident) => {
+            if !from_cli($name) {
+                if let Some(v) = config.$field {
+                    if args.mitm {
+                        args.$field = v;
+                    } else {
+                        diag.push((
+                            Level::Warn,
+                            format!("{} Ignoring config `{}`: requires `mitm`", NAME, $name),
+                        ));
+                    }
+                }
+            }
+        };
+    }
+    overlay_mitm!("remove_tap_restriction", remove_tap_restriction);
+    overlay_mitm!("video_in_motion", video_in_motion);
+    overlay_mitm!("disable_media_sink", disable_media_sink);
+    overlay_mitm!("disable_tts_sink", disable_tts_sink);
+    overlay_mitm!("developer_mode", developer_mode);
+
+    if !from_cli("dpi") {
+        if let Some(v) = config.dpi {
+            if args.mitm {
+                args.dpi = Some(v);
+            } else {
+                diag.push((
+                    Level::Warn,
+                    format!("{} Ignoring config `dpi`: requires `mitm`", NAME),
+                ));
+            }
+        }
+    }
+    if !from_cli("capture") {
+        if let Some(v) = config.capture {
+            args.capture = Some(v);
+        }
+    }
+
+    // string-typed fields that need parsing into their CLI value types
+    if !from_cli("connect") {
+        if let Some(v) = config.connect {
+            match v.parse::<Address>() {
+                Ok(addr) => args.connect = Some(addr),
+                Err(e) => diag.push((
+                    Level::Warn,
+                    format!("{} Invalid connect address {:?}: {}", NAME, v, e),
+                )),
+            }
+        }
+    }
+    if !from_cli("wired") {
+        if let Some(v) = config.wired {
+            match v.parse::<UsbId>() {
+                Ok(id) => args.wired = Some(id),
+                Err(e) => diag.push((
+                    Level::Warn,
+                    format!("{} Invalid wired VID:PID {:?}: {}", NAME, v, e),
+                )),
+            }
+        }
+    }
+
+    diag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+    use std::io::Write;
+
+    /// Parse `cli` args, layer `toml` written to a temp file, return the Args.
+    fn layered(toml: &str, cli: &[&str], file_name: &str) -> Args {
+        let path = std::env::temp_dir().join(file_name);
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(toml.as_bytes())
+            .unwrap();
+        let config_flag = format!("--config={}", path.display());
+        let mut argv = vec!["aa-proxy-rs"];
+        argv.extend_from_slice(cli);
+        argv.push(&config_flag);
+        let matches = Args::command().get_matches_from(argv);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let _ = layer(&mut args, &matches);
+        let _ = std::fs::remove_file(&path);
+        args
+    }
+
+    #[test]
+    fn file_value_overrides_default() {
+        let args = layered("iface = \"wlan9\"\n", &[], "aa-cfg-file-over-default.toml");
+        assert_eq!(args.iface, "wlan9");
+    }
+
+    #[test]
+    fn cli_value_overrides_file() {
+        let args = layered(
+            "iface = \"wlan9\"\n",
+            &["--iface", "eth0"],
+            "aa-cfg-cli-over-file.toml",
+        );
+        assert_eq!(args.iface, "eth0");
+    }
+
+    #[test]
+    fn default_kept_when_neither_sets_it() {
+        // file sets `debug`, but not `iface`, so iface keeps its built-in default
+        let args = layered("debug = true\n", &[], "aa-cfg-default-kept.toml");
+        assert_eq!(args.iface, "wlan0");
+        assert!(args.debug);
+    }
+}
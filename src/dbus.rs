@@ -0,0 +1,282 @@
+use crate::HexdumpLevel;
+use log::info;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use zbus::{connection, interface, object_server::SignalEmitter};
+
+// module name for logging engine
+const NAME: &str = "<i><bright-black> dbus: </>";
+
+/// Well-known bus name and object path for the runtime control interface.
+const BUS_NAME: &str = "org.aaproxy.Proxy1";
+const OBJECT_PATH: &str = "/org/aaproxy/Proxy1";
+
+/// Current high-level connection state, surfaced over D-Bus.
+///
+/// Encoded as a `u8` so it can live in an [`AtomicU8`] inside [`SharedState`]
+/// and be updated from whichever task last changed the connection lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Idle,
+    Advertising,
+    TcpUp,
+    AccessoryUp,
+}
+
+impl ConnectionState {
+    fn as_u8(self) -> u8 {
+        match self {
+            ConnectionState::Idle => 0,
+            ConnectionState::Advertising => 1,
+            ConnectionState::TcpUp => 2,
+            ConnectionState::AccessoryUp => 3,
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => ConnectionState::Advertising,
+            2 => ConnectionState::TcpUp,
+            3 => ConnectionState::AccessoryUp,
+            _ => ConnectionState::Idle,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ConnectionState::Idle => "idle",
+            ConnectionState::Advertising => "advertising",
+            ConnectionState::TcpUp => "tcp",
+            ConnectionState::AccessoryUp => "accessory",
+        }
+    }
+}
+
+/// Runtime-adjustable state shared between the main loop, the io_uring
+/// transfer loop and the D-Bus service.
+///
+/// The MITM knobs used to be plain by-value `Args` fields handed to
+/// `io_loop()`; they now live here behind atomics so the head-unit UI can
+/// toggle them while the proxy is running. `need_restart` is the same
+/// `Notify` the main loop already waits on, so a D-Bus `Reconnect` call just
+/// fires it.
+pub struct SharedState {
+    connection: AtomicU8,
+    /// Forced DPI, `0` meaning "leave untouched".
+    pub dpi: AtomicU16,
+    pub video_in_motion: AtomicBool,
+    pub disable_media_sink: AtomicBool,
+    pub disable_tts_sink: AtomicBool,
+    pub remove_tap_restriction: AtomicBool,
+    pub developer_mode: AtomicBool,
+    pub need_restart: Arc<Notify>,
+}
+
+impl SharedState {
+    pub fn new(
+        dpi: Option<u16>,
+        video_in_motion: bool,
+        disable_media_sink: bool,
+        disable_tts_sink: bool,
+        remove_tap_restriction: bool,
+        developer_mode: bool,
+        need_restart: Arc<Notify>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            connection: AtomicU8::new(ConnectionState::Idle.as_u8()),
+            dpi: AtomicU16::new(dpi.unwrap_or(0)),
+            video_in_motion: AtomicBool::new(video_in_motion),
+            disable_media_sink: AtomicBool::new(disable_media_sink),
+            disable_tts_sink: AtomicBool::new(disable_tts_sink),
+            remove_tap_restriction: AtomicBool::new(remove_tap_restriction),
+            developer_mode: AtomicBool::new(developer_mode),
+            need_restart,
+        })
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        ConnectionState::from_u8(self.connection.load(Ordering::Relaxed))
+    }
+
+    pub fn set_connection_state(&self, state: ConnectionState) {
+        self.connection.store(state.as_u8(), Ordering::Relaxed);
+    }
+
+    pub fn dpi(&self) -> Option<u16> {
+        match self.dpi.load(Ordering::Relaxed) {
+            0 => None,
+            v => Some(v),
+        }
+    }
+
+    pub fn video_in_motion(&self) -> bool {
+        self.video_in_motion.load(Ordering::Relaxed)
+    }
+
+    pub fn disable_media_sink(&self) -> bool {
+        self.disable_media_sink.load(Ordering::Relaxed)
+    }
+
+    pub fn disable_tts_sink(&self) -> bool {
+        self.disable_tts_sink.load(Ordering::Relaxed)
+    }
+
+    pub fn remove_tap_restriction(&self) -> bool {
+        self.remove_tap_restriction.load(Ordering::Relaxed)
+    }
+
+    pub fn developer_mode(&self) -> bool {
+        self.developer_mode.load(Ordering::Relaxed)
+    }
+}
+
+/// `org.aaproxy.Proxy1` D-Bus interface implementation.
+struct Proxy1 {
+    state: Arc<SharedState>,
+}
+
+#[interface(name = "org.aaproxy.Proxy1")]
+impl Proxy1 {
+    /// Current connection state: `idle`, `advertising`, `tcp` or `accessory`.
+    fn state(&self) -> String {
+        self.state.connection_state().as_str().to_string()
+    }
+
+    /// Force a reconnect by firing the same `need_restart` notify the main
+    /// loop waits on.
+    fn reconnect(&self) {
+        info!("{} 🔄 Reconnect requested over D-Bus", NAME);
+        self.state.need_restart.notify_one();
+    }
+
+    /// Forced DPI, `0` meaning the phone's own DPI is kept.
+    #[zbus(property)]
+    fn dpi(&self) -> u16 {
+        self.state.dpi.load(Ordering::Relaxed)
+    }
+
+    #[zbus(property)]
+    fn set_dpi(&self, value: u16) {
+        self.state.dpi.store(value, Ordering::Relaxed);
+    }
+
+    #[zbus(property)]
+    fn video_in_motion(&self) -> bool {
+        self.state.video_in_motion.load(Ordering::Relaxed)
+    }
+
+    #[zbus(property)]
+    fn set_video_in_motion(&self, value: bool) {
+        self.state.video_in_motion.store(value, Ordering::Relaxed);
+    }
+
+    #[zbus(property)]
+    fn disable_media_sink(&self) -> bool {
+        self.state.disable_media_sink.load(Ordering::Relaxed)
+    }
+
+    #[zbus(property)]
+    fn set_disable_media_sink(&self, value: bool) {
+        self.state.disable_media_sink.store(value, Ordering::Relaxed);
+    }
+
+    #[zbus(property)]
+    fn remove_tap_restriction(&self) -> bool {
+        self.state.remove_tap_restriction.load(Ordering::Relaxed)
+    }
+
+    #[zbus(property)]
+    fn set_remove_tap_restriction(&self, value: bool) {
+        self.state
+            .remove_tap_restriction
+            .store(value, Ordering::Relaxed);
+    }
+
+    #[zbus(property)]
+    fn disable_tts_sink(&self) -> bool {
+        self.state.disable_tts_sink.load(Ordering::Relaxed)
+    }
+
+    #[zbus(property)]
+    fn set_disable_tts_sink(&self, value: bool) {
+        self.state.disable_tts_sink.store(value, Ordering::Relaxed);
+    }
+
+    #[zbus(property)]
+    fn developer_mode(&self) -> bool {
+        self.state.developer_mode.load(Ordering::Relaxed)
+    }
+
+    #[zbus(property)]
+    fn set_developer_mode(&self, value: bool) {
+        self.state.developer_mode.store(value, Ordering::Relaxed);
+    }
+
+    /// Emitted when a phone connects (TCP or USB accessory is up).
+    #[zbus(signal)]
+    async fn phone_connected(emitter: &SignalEmitter<'_>, state: &str) -> zbus::Result<()>;
+
+    /// Emitted when the phone disconnects and we return to idle.
+    #[zbus(signal)]
+    async fn phone_disconnected(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// Register the `org.aaproxy.Proxy1` service on the system bus and keep the
+/// connection alive for the lifetime of the process.
+///
+/// Returns the [`zbus::Connection`] so the caller can hold it (dropping it
+/// would unregister the service) and emit signals through it.
+pub async fn serve(state: Arc<SharedState>) -> zbus::Result<zbus::Connection> {
+    let conn = connection::Builder::system()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Proxy1 { state })?
+        .build()
+        .await?;
+    info!("{} 🚌 D-Bus service registered as {}", NAME, BUS_NAME);
+    Ok(conn)
+}
+
+/// Emit the appropriate connect/disconnect signal for a state transition and
+/// record the new state.
+pub async fn announce(conn: &zbus::Connection, state: &Arc<SharedState>, new: ConnectionState) {
+    let previous = state.connection_state();
+    state.set_connection_state(new);
+
+    let emitter = match SignalEmitter::new(conn, OBJECT_PATH) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    let connected = matches!(new, ConnectionState::TcpUp | ConnectionState::AccessoryUp);
+    let was_connected = matches!(
+        previous,
+        ConnectionState::TcpUp | ConnectionState::AccessoryUp
+    );
+    if connected && !was_connected {
+        let _ = Proxy1::phone_connected(&emitter, new.as_str()).await;
+    } else if !connected && was_connected {
+        let _ = Proxy1::phone_disconnected(&emitter).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_state_u8_round_trip() {
+        for state in [
+            ConnectionState::Idle,
+            ConnectionState::Advertising,
+            ConnectionState::TcpUp,
+            ConnectionState::AccessoryUp,
+        ] {
+            assert_eq!(ConnectionState::from_u8(state.as_u8()), state);
+        }
+    }
+
+    #[test]
+    fn connection_state_unknown_u8_is_idle() {
+        assert_eq!(ConnectionState::from_u8(42), ConnectionState::Idle);
+    }
+}
@@ -1,18 +1,25 @@
 mod aoa;
 mod bluetooth;
+mod capture;
+mod config;
+mod dbus;
 mod io_uring;
 mod mitm;
+mod persist;
+mod state_machine;
 mod usb_gadget;
 mod usb_stream;
 
 use bluer::Address;
 use bluetooth::bluetooth_setup_connection;
 use bluetooth::bluetooth_stop;
-use clap::Parser;
+use dbus::SharedState;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use humantime::format_duration;
 use io_uring::io_loop;
 use simple_config_parser::Config;
 use simplelog::*;
+use state_machine::StateMachine;
 use usb_gadget::uevent_listener;
 use usb_gadget::UsbGadgetState;
 
@@ -28,10 +35,13 @@ use tokio::time::Instant;
 const NAME: &str = "<i><bright-black> main: </>";
 
 const DEFAULT_WLAN_ADDR: &str = "10.0.0.1";
+/// Wildcard MAC meaning "auto-connect to any / the saved phone".
+const WILDCARD_ADDRESS: Address = Address([0; 6]);
 const TCP_SERVER_PORT: i32 = 5288;
 const TCP_DHU_PORT: i32 = 5277;
 
-#[derive(clap::ValueEnum, Default, Debug, PartialEq, PartialOrd, Clone, Copy)]
+#[derive(clap::ValueEnum, Default, Debug, PartialEq, PartialOrd, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum HexdumpLevel {
     #[default]
     Disabled,
@@ -83,6 +93,11 @@ struct Args {
     #[clap(long, default_value_t, value_enum, requires("debug"))]
     hexdump_level: HexdumpLevel,
 
+    /// Capture the tapped protocol stream to a .pcap file for Wireshark.
+    /// The --hexdump-level selection decides which stage/direction is captured.
+    #[clap(long, value_parser)]
+    capture: Option<PathBuf>,
+
     /// Enable legacy mode
     #[clap(short, long)]
     legacy: bool,
@@ -160,6 +175,10 @@ struct Args {
     /// instead of real HU device (will listen on TCP 5277 port)
     #[clap(long)]
     dhu: bool,
+
+    /// Config file path (TOML or YAML); CLI flags override its values
+    #[clap(long, value_parser, default_value = "/etc/aa-proxy-rs.toml")]
+    config: PathBuf,
 }
 
 #[derive(Clone)]
@@ -256,10 +275,38 @@ fn logging_init(debug: bool, log_path: &PathBuf) {
     }
 }
 
-async fn tokio_main(args: Args, need_restart: Arc<Notify>, tcp_start: Arc<Notify>) {
+/// Update the shared connection state and, if the D-Bus service came up,
+/// emit the matching connect/disconnect signal.
+async fn announce_state(
+    conn: &Option<zbus::Connection>,
+    state: &Arc<SharedState>,
+    new: dbus::ConnectionState,
+) {
+    match conn {
+        Some(conn) => dbus::announce(conn, state, new).await,
+        None => state.set_connection_state(new),
+    }
+}
+
+async fn tokio_main(
+    args: Args,
+    state: Arc<SharedState>,
+    need_restart: Arc<Notify>,
+    tcp_start: Arc<Notify>,
+) {
     let accessory_started = Arc::new(Notify::new());
     let accessory_started_cloned = accessory_started.clone();
 
+    // register the runtime control service; keep the connection alive for the
+    // whole process by holding it in this task
+    let dbus_conn = match dbus::serve(state.clone()).await {
+        Ok(conn) => Some(conn),
+        Err(e) => {
+            error!("{} Could not register D-Bus service: {}", NAME, e);
+            None
+        }
+    };
+
     let wifi_conf = {
         if !args.wired.is_some() {
             Some(init_wifi_config(&args.iface, args.hostapd_conf))
@@ -275,7 +322,22 @@ async fn tokio_main(args: Args, need_restart: Arc<Notify>, tcp_start: Arc<Notify
         }
         usb = Some(UsbGadgetState::new(args.legacy, args.udc));
     }
+    let mut sm = StateMachine::new();
+    let mut first = true;
     loop {
+        // blanket settle before re-initializing: every re-entry (adapter/index
+        // removal, a `need_restart` reconnect, a failed bring-up) can leave the
+        // kernel mid-teardown of the previous socket, so we give it a fixed
+        // moment to settle. This is a per-iteration settle rather than a
+        // debounce scoped to the removal event itself — the removal detection
+        // lives in the io/uevent path, so the loop cannot see that edge here.
+        if !first {
+            tokio::time::sleep(state_machine::ADAPTER_REMOVAL_DEBOUNCE).await;
+        }
+        first = false;
+
+        sm.turning_on();
+        debug!("{} lifecycle: {:?}", NAME, sm.state());
         if let Some(ref mut usb) = usb {
             if let Err(e) = usb.init() {
                 error!("{} 🔌 USB init error: {}", NAME, e);
@@ -284,24 +346,66 @@ async fn tokio_main(args: Args, need_restart: Arc<Notify>, tcp_start: Arc<Notify
 
         let mut bt_stop = None;
         if let Some(ref wifi_conf) = wifi_conf {
+            // when auto-connecting (wildcard MAC), prefer the last bonded phone
+            // and reconnect to it directly before going back to advertising
+            let persisted = if args.connect == Some(WILDCARD_ADDRESS) {
+                persist::load(&args.config)
+            } else {
+                None
+            };
+            let mut direct_tries = 0;
+
             loop {
+                // target the persisted device directly for the first few
+                // attempts, then fall back to the configured (wildcard) flow
+                let connect = match persisted {
+                    Some(addr) if direct_tries < persist::DIRECT_RECONNECT_TRIES => Some(addr),
+                    _ => args.connect,
+                };
+                let direct = connect != args.connect;
+                if !direct && args.advertise {
+                    announce_state(&dbus_conn, &state, dbus::ConnectionState::Advertising).await;
+                }
+
                 match bluetooth_setup_connection(
-                    args.advertise,
+                    args.advertise && !direct,
                     args.btalias.clone(),
-                    args.connect,
+                    connect,
                     wifi_conf.clone(),
                     tcp_start.clone(),
                     args.keepalive,
                 )
                 .await
                 {
-                    Ok(state) => {
+                    Ok((bt_state, peer)) => {
+                        // remember the phone we actually linked to for next
+                        // time; `bluetooth_setup_connection` resolves the real
+                        // peer even when we asked for the wildcard address
+                        if args.connect == Some(WILDCARD_ADDRESS) && peer != WILDCARD_ADDRESS {
+                            persist::save(&args.config, peer);
+                        }
+                        announce_state(&dbus_conn, &state, dbus::ConnectionState::TcpUp).await;
                         // we're ready, gracefully shutdown bluetooth in task
-                        bt_stop = Some(tokio::spawn(async move { bluetooth_stop(state).await }));
+                        bt_stop =
+                            Some(tokio::spawn(async move { bluetooth_stop(bt_state).await }));
                         break;
                     }
                     Err(e) => {
                         error!("{} Bluetooth error: {}", NAME, e);
+                        if direct {
+                            direct_tries += 1;
+                            warn!(
+                                "{} Direct reconnect attempt {}/{} failed",
+                                NAME,
+                                direct_tries,
+                                persist::DIRECT_RECONNECT_TRIES
+                            );
+                        }
+                        if sm.failed() {
+                            // repeated failures: power-cycle the adapter to
+                            // recover a wedged dongle before trying again
+                            state_machine::try_reset_adapter().await;
+                        }
                         info!("{} Trying to recover...", NAME);
                         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                     }
@@ -310,10 +414,28 @@ async fn tokio_main(args: Args, need_restart: Arc<Notify>, tcp_start: Arc<Notify
         }
 
         if let Some(ref mut usb) = usb {
-            usb.enable_default_and_wait_for_accessory(accessory_started.clone())
-                .await;
+            if let Err(e) = usb
+                .enable_default_and_wait_for_accessory(accessory_started.clone())
+                .await
+            {
+                error!("{} 🔌 USB accessory bring-up error: {}", NAME, e);
+                // the USB side failed just like a Bluetooth bring-up would:
+                // count it towards the adapter power-cycle threshold
+                if sm.failed() {
+                    state_machine::try_reset_adapter().await;
+                }
+                if let Some(bt_stop) = bt_stop {
+                    let _ = bt_stop.await;
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+            announce_state(&dbus_conn, &state, dbus::ConnectionState::AccessoryUp).await;
         }
 
+        // reached a fully-connected state: clear the failure counter
+        sm.on();
+
         if let Some(bt_stop) = bt_stop {
             // wait for bluetooth stop properly
             let _ = bt_stop.await;
@@ -321,6 +443,8 @@ async fn tokio_main(args: Args, need_restart: Arc<Notify>, tcp_start: Arc<Notify
 
         // wait for restart
         need_restart.notified().await;
+        sm.turning_off();
+        announce_state(&dbus_conn, &state, dbus::ConnectionState::Idle).await;
 
         // TODO: make proper main loop with cancelation
         info!(
@@ -333,8 +457,21 @@ async fn tokio_main(args: Args, need_restart: Arc<Notify>, tcp_start: Arc<Notify
 
 fn main() {
     let started = Instant::now();
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).expect("Cannot parse arguments");
+    // layer /etc/aa-proxy-rs.toml (or --config) under the CLI flags; this runs
+    // before logging_init so a file-supplied `debug` takes effect, so layering
+    // cannot log directly — it hands back buffered diagnostics to replay once
+    // the logger is installed
+    let config_diag = config::layer(&mut args, &matches);
     logging_init(args.debug, &args.logfile);
+    for (level, message) in config_diag {
+        match level {
+            log::Level::Error => error!("{}", message),
+            log::Level::Warn => warn!("{}", message),
+            _ => info!("{}", message),
+        }
+    }
 
     let stats_interval = {
         if args.stats_interval == 0 {
@@ -377,19 +514,41 @@ fn main() {
     let tcp_start = Arc::new(Notify::new());
     let tcp_start_cloned = tcp_start.clone();
     let mitm = args.mitm;
-    let dpi = args.dpi;
-    let developer_mode = args.developer_mode;
-    let disable_media_sink = args.disable_media_sink;
-    let disable_tts_sink = args.disable_tts_sink;
-    let remove_tap_restriction = args.remove_tap_restriction;
-    let video_in_motion = args.video_in_motion;
     let hex_requested = args.hexdump_level;
+    // open the pcap capture up front so a bad path fails at startup, not mid
+    // stream; the handle is threaded into io_loop where frames are tapped
+    let capture = match args.capture {
+        Some(ref path) => match capture::Capture::create(path, args.hexdump_level) {
+            Ok(cap) => {
+                info!("{} 🧲 Capturing tapped stream to {:?}", NAME, path);
+                Some(cap)
+            }
+            Err(e) => {
+                error!("{} Could not create capture file {:?}: {}", NAME, path, e);
+                None
+            }
+        },
+        None => None,
+    };
     let wired = args.wired.clone();
     let dhu = args.dhu;
 
+    // runtime-adjustable MITM knobs + connection state, shared with the
+    // D-Bus control service and the io_uring transfer loop
+    let state = SharedState::new(
+        args.dpi,
+        args.video_in_motion,
+        args.disable_media_sink,
+        args.disable_tts_sink,
+        args.remove_tap_restriction,
+        args.developer_mode,
+        need_restart.clone(),
+    );
+    let state_cloned = state.clone();
+
     // build and spawn main tokio runtime
     let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
-    runtime.spawn(async move { tokio_main(args, need_restart, tcp_start).await });
+    runtime.spawn(async move { tokio_main(args, state, need_restart, tcp_start).await });
 
     // start tokio_uring runtime simultaneously
     let _ = tokio_uring::start(io_loop(
@@ -398,13 +557,9 @@ fn main() {
         tcp_start_cloned,
         read_timeout,
         mitm,
-        dpi,
-        developer_mode,
-        disable_media_sink,
-        disable_tts_sink,
-        remove_tap_restriction,
-        video_in_motion,
+        state_cloned,
         hex_requested,
+        capture,
         wired,
         dhu,
     ));
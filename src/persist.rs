@@ -0,0 +1,47 @@
+use bluer::Address;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+// module name for logging engine
+const NAME: &str = "<i><bright-black> persist: </>";
+
+/// File name, stored next to the config file, holding the last phone we
+/// successfully connected to.
+const LAST_DEVICE_FILE: &str = "last_device";
+
+/// Number of direct reconnect attempts to the persisted device before falling
+/// back to full BLE advertising/discovery.
+pub const DIRECT_RECONNECT_TRIES: u32 = 3;
+
+/// Path of the persisted-address file, placed alongside `config`.
+fn path_for(config: &Path) -> PathBuf {
+    config
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(LAST_DEVICE_FILE)
+}
+
+/// Load the last successfully-connected phone address, if any.
+pub fn load(config: &Path) -> Option<Address> {
+    let path = path_for(config);
+    let raw = std::fs::read_to_string(&path).ok()?;
+    match raw.trim().parse::<Address>() {
+        Ok(addr) => {
+            info!("{} 💾 Last bonded phone: {}", NAME, addr);
+            Some(addr)
+        }
+        Err(e) => {
+            warn!("{} Ignoring malformed {:?}: {}", NAME, path, e);
+            None
+        }
+    }
+}
+
+/// Persist the address of the phone we just connected to, so we can reconnect
+/// directly next time instead of re-advertising.
+pub fn save(config: &Path, addr: Address) {
+    let path = path_for(config);
+    if let Err(e) = std::fs::write(&path, addr.to_string()) {
+        warn!("{} Could not persist last device to {:?}: {}", NAME, path, e);
+    }
+}
@@ -0,0 +1,134 @@
+use log::{error, warn};
+use std::time::Duration;
+
+// module name for logging engine
+const NAME: &str = "<i><bright-black> state: </>";
+
+/// Number of consecutive failures after which the Bluetooth adapter is
+/// power-cycled before the next connection attempt.
+pub const RESET_ON_RESTART_COUNT: u32 = 2;
+
+/// Settle delay applied before every re-entry of the main connection loop
+/// (after an adapter/index removal, a reconnect or a failed bring-up), to give
+/// the kernel time to tear the previous socket down. It is not tied to the
+/// removal event itself.
+pub const ADAPTER_REMOVAL_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// How long the adapter is kept powered off during a reset.
+const POWER_CYCLE_DELAY: Duration = Duration::from_secs(2);
+
+/// High-level lifecycle of the proxy connection, modeled after the Floss
+/// `state_machine.rs` `ProcessState`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessState {
+    Off,
+    TurningOn,
+    On,
+    TurningOff,
+}
+
+/// Tracks the connection lifecycle and the consecutive-failure counter that
+/// drives adapter recovery.
+pub struct StateMachine {
+    state: ProcessState,
+    failures: u32,
+}
+
+impl StateMachine {
+    pub fn new() -> Self {
+        Self {
+            state: ProcessState::Off,
+            failures: 0,
+        }
+    }
+
+    pub fn state(&self) -> ProcessState {
+        self.state
+    }
+
+    /// Mark the beginning of a bring-up attempt.
+    pub fn turning_on(&mut self) {
+        self.state = ProcessState::TurningOn;
+    }
+
+    /// Record a successful `On` transition and clear the failure counter.
+    pub fn on(&mut self) {
+        self.state = ProcessState::On;
+        self.failures = 0;
+    }
+
+    /// Mark that the connection is being torn down (disconnect / restart).
+    pub fn turning_off(&mut self) {
+        self.state = ProcessState::TurningOff;
+    }
+
+    /// Record a failed bring-up. Returns `true` once the consecutive-failure
+    /// count reaches [`RESET_ON_RESTART_COUNT`], signalling that the adapter
+    /// should be power-cycled before the next attempt; in that case the
+    /// counter is cleared so a single wedge triggers exactly one power-cycle
+    /// rather than one on every subsequent failure.
+    pub fn failed(&mut self) -> bool {
+        self.state = ProcessState::TurningOff;
+        self.failures += 1;
+        if self.failures >= RESET_ON_RESTART_COUNT {
+            self.failures = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Power-cycle the default Bluetooth adapter through `bluer`: power off, wait
+/// for the kernel to settle, power back on. Used to recover a wedged dongle
+/// after repeated connection failures.
+pub async fn reset_adapter() -> bluer::Result<()> {
+    warn!("{} 🔌 Power-cycling Bluetooth adapter to recover", NAME);
+    let session = bluer::Session::new().await?;
+    let adapter = session.default_adapter().await?;
+    adapter.set_powered(false).await?;
+    tokio::time::sleep(POWER_CYCLE_DELAY).await;
+    adapter.set_powered(true).await?;
+    Ok(())
+}
+
+/// Run [`reset_adapter`] and log (but do not propagate) any failure, since a
+/// failed reset should still fall through to another normal attempt.
+pub async fn try_reset_adapter() {
+    if let Err(e) = reset_adapter().await {
+        error!("{} Adapter reset failed: {}", NAME, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_triggers_reset_at_threshold_then_clears() {
+        let mut sm = StateMachine::new();
+        // first failure is below the default threshold of 2
+        assert!(!sm.failed());
+        // second failure reaches the threshold and requests a power-cycle
+        assert!(sm.failed());
+        // counter cleared on trigger: a single wedge causes exactly one reset,
+        // so the next failure is below threshold again
+        assert!(!sm.failed());
+    }
+
+    #[test]
+    fn on_clears_the_failure_counter() {
+        let mut sm = StateMachine::new();
+        assert!(!sm.failed());
+        sm.on();
+        assert_eq!(sm.state(), ProcessState::On);
+        // counter reset by `on`, so the next failure starts from zero
+        assert!(!sm.failed());
+    }
+}